@@ -29,19 +29,116 @@ pub const FLUSH_DICTIONARY: u16 = 4093;
 pub const EOF: u16 = 4094;
 pub const EOS: u16 = 4095;
 
+// Variable-width code parameters. The four reserved control codes live at the
+// very top of the 12-bit code space, so NUM_CONTROL slots are kept clear at the
+// top of every width and the control codes float up with the width until they
+// reach their canonical values at 12 bits.
+pub const MIN_CODE_WIDTH: u8 = 9;
+pub const MAX_CODE_WIDTH: u8 = 12;
+const NUM_CONTROL: u16 = (1 << MAX_CODE_WIDTH) - NOOP; // 4096 - 4091 = 5
+
+// The code width to use once the dictionary's next code to be assigned is
+// `count` (the encoder's `next_code`, or its decoder-side equivalent). The
+// width grows by one bit each time the next code would not fit alongside the
+// reserved control slots, capping at MAX_CODE_WIDTH. Both the encoder and the
+// decoder derive the width from their own dictionary size alone, which is
+// bounded by `max_dict_size`, never from a count of transmitted codes.
+fn code_width(count: u16) -> u8 {
+    let mut w = MIN_CODE_WIDTH;
+    while w < MAX_CODE_WIDTH && count as u32 > (1u32 << w) - 1 - NUM_CONTROL as u32 {
+        w += 1;
+    }
+    return w;
+}
+
+// Tunable knobs for the encoder. Built with the builder methods, e.g.
+// `Config::new().variable_width(true).disable_flushing()`, and passed to
+// `compress_with`/`decompress_with`. The defaults reproduce the behavior of the
+// plain `compress`/`decompress` entry points.
+//
+// Note that `max_dict_size` is not recorded in the frame, so a stream produced
+// with a non-default dictionary size must be decompressed with a matching
+// `Config` (the code-width mode, by contrast, travels in the frame flags).
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub max_dict_size: usize,
+    pub flush_size_threshold: usize,
+    // The adaptive dictionary flush fires once the dictionary has grown past
+    // `flush_size_threshold` entries *and* the running compression ratio is
+    // worse than this percentage. `None` disables adaptive flushing entirely.
+    pub flush_ratio_threshold: Option<usize>,
+    pub variable_width: bool,
+    pub debug: bool,
+    pub verbose: bool,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        return Config {
+            max_dict_size: MAX_DICT_SIZE,
+            flush_size_threshold: 4000,
+            flush_ratio_threshold: Some(200),
+            variable_width: false,
+            debug: false,
+            verbose: false,
+        };
+    }
+
+    // Clamped to `1..=MAX_DICT_SIZE`: a size above MAX_DICT_SIZE would let
+    // assigned codes collide with the reserved control codes (NOOP and
+    // friends, just above MAX_DICT_SIZE) or overflow `next_code` once the
+    // 12-bit code space is exhausted, silently corrupting output instead of
+    // just capping the dictionary the way the caller intended.
+    pub fn max_dict_size(mut self, size: usize) -> Config {
+        self.max_dict_size = size.clamp(1, MAX_DICT_SIZE);
+        return self;
+    }
+
+    pub fn flush_size_threshold(mut self, size: usize) -> Config {
+        self.flush_size_threshold = size;
+        return self;
+    }
+
+    pub fn flush_ratio_threshold(mut self, ratio: Option<usize>) -> Config {
+        self.flush_ratio_threshold = ratio;
+        return self;
+    }
+
+    // Turn off the adaptive dictionary flush (equivalent to passing `None` to
+    // `flush_ratio_threshold`). Useful for already-homogeneous input.
+    pub fn disable_flushing(mut self) -> Config {
+        self.flush_ratio_threshold = None;
+        return self;
+    }
+
+    pub fn variable_width(mut self, variable_width: bool) -> Config {
+        self.variable_width = variable_width;
+        return self;
+    }
+
+    pub fn debug(mut self, debug: bool) -> Config {
+        self.debug = debug;
+        return self;
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Config {
+        self.verbose = verbose;
+        return self;
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        return Config::new();
+    }
+}
+
 macro_rules! debug {
     ($debug:expr) => (if $debug { eprintln!(); });
     ($debug:expr, $fmt:expr) => (if $debug { eprintln!($fmt); });
     ($debug:expr, $fmt:expr, $($arg:tt)*) => (if $debug { eprintln!($fmt, $($arg)*); });
 }
 
-fn print_dictionary(d: &[Vec<u8>]) {
-    eprintln!("Dictionary:");
-    for i in 0..d.len() {
-        eprintln!("\t{:04}:\t{:02X?}", i, &d[i]);
-    }
-}
-
 // Returns the compression ratio as a floating point percentage.
 // A value > 100 means that the file increased in size instead of decreasing.
 pub fn compression_ratio(bytes_uncompressed: usize, bytes_compressed: usize) -> f64 {
@@ -53,52 +150,84 @@ pub fn compression_ratio(bytes_uncompressed: usize, bytes_compressed: usize) ->
 
 #[derive(Debug)]
 struct Compressor {
-    dict: HashMap<Vec<u8>,u16>,
-    key: Vec<u8>,
-    value: Option<u16>,
+    dict: HashMap<(u16,u8),u16>,
+    w: Option<u16>,
+    next_code: u16,
     write_state: Option<u16>,
+    // Variable-width bit packer state (only used when `variable_width` is set).
+    variable_width: bool,
+    bit_buffer: u32,
+    bit_count: u8,
+    // Running byte counts, kept as state so the encoder can be driven one byte
+    // at a time (see compress_byte) as well as over a whole reader.
+    bytes_read: usize,
+    bytes_written: usize,
+    bytes_read_before_flush: usize,
+    bytes_written_before_flush: usize,
+    // Tunables copied out of the Config this compressor was built from.
+    max_dict_size: usize,
+    flush_size_threshold: usize,
+    flush_ratio_threshold: Option<usize>,
     debug: bool,
     verbose: bool,
 }
 
 impl Compressor {
-    fn new() -> Compressor {
+    fn with_config(config: &Config) -> Compressor {
         let mut c = Compressor {
-            dict: HashMap::with_capacity(MAX_DICT_SIZE),
-            key: Vec::with_capacity(MAX_DICT_SIZE),
-            value: None,
+            dict: HashMap::with_capacity(config.max_dict_size),
+            w: None,
+            next_code: 256,
             write_state: None,
-            debug: false,
-            verbose: false,
+            variable_width: config.variable_width,
+            bit_buffer: 0,
+            bit_count: 0,
+            bytes_read: 0,
+            bytes_written: 0,
+            bytes_read_before_flush: 0,
+            bytes_written_before_flush: 0,
+            max_dict_size: config.max_dict_size,
+            flush_size_threshold: config.flush_size_threshold,
+            flush_ratio_threshold: config.flush_ratio_threshold,
+            debug: config.debug,
+            verbose: config.verbose,
         };
         c.flush_dictionary();
         return c;
     }
 
-    fn with_debug(debug: bool) -> Compressor {
-        let mut c = Compressor::new();
-        c.debug = debug;
-        return c;
+    fn with_mode(debug: bool, variable_width: bool) -> Compressor {
+        return Compressor::with_config(&Config::new().debug(debug).variable_width(variable_width));
     }
 
     fn print_dictionary(&self) {
-        let mut d: Vec<Vec<u8>> = Vec::with_capacity(self.dict.len());
-        d.resize(self.dict.len(), vec![]);
+        // The literal codes 0..=255 are implicit, so only the extended
+        // (prefix, byte) -> code entries are held in the map.
+        let mut d: Vec<(u16,u8)> = Vec::with_capacity(self.dict.len());
+        d.resize(self.dict.len(), (0,0));
+        eprintln!("Dictionary:");
         for (k,v) in &self.dict {
-            d[*v as usize] = k.clone();
+            d[(*v as usize) - 256] = *k;
+        }
+        for i in 0..d.len() {
+            eprintln!("\t{:04}:\t({:04}, {:02X})", i + 256, d[i].0, d[i].1);
         }
-        print_dictionary(&d);
     }
 
     fn flush_dictionary(&mut self) {
+        // The 256 single-byte literals are represented implicitly (code == byte),
+        // so the map only ever holds the extended entries assigned from 256 up.
         self.dict.clear();
-        // Initialize dictionary
-        for n in 0..=255 {
-            self.dict.insert(vec!(n), n as u16);
-        }
+        self.next_code = 256;
     }
 
+    // Emit a single code. In variable-width mode the code is packed at the
+    // current width through the bit buffer; otherwise it uses the fixed
+    // 3-bytes-per-2-codes packing that the original stream format relies on.
     fn encode(&mut self, value: u16, output: &mut dyn Write) -> std::io::Result<usize> {
+        if self.variable_width {
+            return self.encode_var(value, output);
+        }
         let mut bytes_written: usize = 0;
         self.write_state = match self.write_state {
             None => Some(value),
@@ -120,71 +249,127 @@ impl Compressor {
         Ok(bytes_written)
     }
 
+    // Pack a code at the current variable width, LSB first, draining whole
+    // bytes out of the bit buffer as they accumulate. Control codes are shifted
+    // down so they stay in the top NUM_CONTROL slots of the current width. The
+    // width is driven by `next_code` (the dictionary's actual size, already
+    // bounded by `max_dict_size`), not by a count of codes emitted, so it stops
+    // growing exactly when the dictionary stops growing (e.g. once capped by
+    // `Config::max_dict_size`, or on long incompressible runs).
+    fn encode_var(&mut self, value: u16, output: &mut dyn Write) -> std::io::Result<usize> {
+        let mut bytes_written: usize = 0;
+        let width = code_width(self.next_code);
+        let packed: u32 = if value >= NOOP {
+            value as u32 + (1u32 << width) - (1u32 << MAX_CODE_WIDTH)
+        } else {
+            value as u32
+        };
+
+        if self.verbose {
+            debug!(self.debug, "Wrote: {:?} at {:?} bits, Value: {:?}", &packed, &width, &value);
+        }
+
+        self.bit_buffer |= packed << self.bit_count;
+        self.bit_count += width;
+        while self.bit_count >= 8 {
+            bytes_written += output.write(&[(self.bit_buffer & 0xFF) as u8])?;
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+
+        Ok(bytes_written)
+    }
+
     fn compress(&mut self, input: &mut dyn Read, output: &mut dyn Write) -> std::io::Result<(usize,usize)> {
         debug!(self.debug, "Compressing");
-        let mut bytes_read: usize = 0;
-        let mut bytes_written: usize = 0;
-        let mut bytes_read_before_flush: usize = 0;
-        let mut bytes_written_before_flush: usize = 0;
         for byte in input.bytes() {
-            bytes_read += 1;
-            let b = byte?;
-            self.key.push(b);
-            let key: Vec<u8> = self.key.clone().into();
-            match self.dict.get(&key) {
-                // No match found
-                None => {
-                    if let Some(last_value) = self.value {
-                        // If we had a previous value, write it out
-                        bytes_written += self.encode(last_value, output)?;
-                        self.value = None;
-                    }
+            self.compress_byte(byte?, output)?;
+        }
+        if let Some(w) = self.w {
+            self.bytes_written += self.encode(w, output)?;
+            self.w = None;
+        }
+
+        self.bytes_written += self.flush(output)?;
 
-                    bytes_written += self.encode(b as u16, output)?;
+        debug!(self.debug, "Done");
+        debug!(self.debug, "------------------------------");
+        return Ok((self.bytes_read, self.bytes_written));
+    }
+
+    // Feed a single input byte to the encoder, emitting any codes it produces.
+    // This is the per-byte core shared by `compress` and the streaming writer.
+    fn compress_byte(&mut self, b: u8, output: &mut dyn Write) -> std::io::Result<()> {
+        self.bytes_read += 1;
+        match self.w {
+            // First byte of the stream: start matching from its literal code.
+            None => self.w = Some(b as u16),
+            Some(w) => match self.dict.get(&(w, b)) {
+                // (w, b) is in the dictionary, keep extending the match.
+                Some(code) => self.w = Some(*code),
+                // No match: emit w, add (w, b), and restart from b.
+                None => {
+                    self.bytes_written += self.encode(w, output)?;
 
                     // Add an entry to the dictionary if there is space
-                    if self.dict.len() <= MAX_DICT_SIZE {
+                    if (self.next_code as usize) <= self.max_dict_size {
                         if self.verbose {
-                            debug!(self.debug, "Adding {:?} as {:?}", &key, self.dict.len());
+                            debug!(self.debug, "Adding ({:?}, {:02X}) as {:?}", &w, &b, self.next_code);
                         }
-                        self.dict.insert(key, self.dict.len() as u16);
-                    }  
+                        self.dict.insert((w, b), self.next_code);
+                        self.next_code += 1;
+                    }
 
-                    // Clear out the key and reset it to the c
-                    self.key.clear();
+                    self.w = Some(b as u16);
                 },
-                // Match found, look for a longer match
-                Some(i) => self.value = Some(*i),
-            }
+            },
+        }
 
-            // Check compression ratio and dictionary size
-            let size = self.dict.len();
-            let r = bytes_read - bytes_read_before_flush;
-            let w = bytes_written - bytes_written_before_flush;
-            let ratio = compression_ratio(r, w) as usize;
-            // TODO: Make this configurable
-            if size > 4000 && ratio > 200 {
+        // Check compression ratio and dictionary size
+        let size = self.next_code as usize;
+        let r = self.bytes_read - self.bytes_read_before_flush;
+        let w = self.bytes_written - self.bytes_written_before_flush;
+        let ratio = compression_ratio(r, w) as usize;
+        if let Some(ratio_threshold) = self.flush_ratio_threshold {
+            if size > self.flush_size_threshold && ratio > ratio_threshold {
                 debug!(self.debug, "Flushing Dictionary. Bytes Read: {}, Bytes Written: {}, Compression Ratio: {:3} %", r, w, ratio);
-                bytes_written += self.encode(FLUSH_DICTIONARY, output)?;
-                bytes_written += self.flush(output)?;
+                // Emit the in-progress match before resetting so the decoder stays in sync.
+                if let Some(w) = self.w {
+                    self.bytes_written += self.encode(w, output)?;
+                    self.w = None;
+                }
+                self.bytes_written += self.encode(FLUSH_DICTIONARY, output)?;
+                self.bytes_written += self.flush(output)?;
                 self.flush_dictionary();
-                bytes_written_before_flush = bytes_written;
-                bytes_read_before_flush = bytes_read;
+                self.bytes_written_before_flush = self.bytes_written;
+                self.bytes_read_before_flush = self.bytes_read;
             }
-
-        }
-        if let Some(last_value) = self.value {
-            bytes_written += self.encode(last_value, output)?;
         }
+        return Ok(());
+    }
 
+    // Emit the final in-progress match, then the end-of-file marker. Used to
+    // close out a stream that was fed a byte at a time (see CompressWriter).
+    fn finish(&mut self, output: &mut dyn Write) -> std::io::Result<usize> {
+        let mut bytes_written: usize = 0;
+        if let Some(w) = self.w {
+            bytes_written += self.encode(w, output)?;
+            self.w = None;
+        }
         bytes_written += self.flush(output)?;
-
-        debug!(self.debug, "Done");
-        debug!(self.debug, "------------------------------");
-        return Ok((bytes_read, bytes_written));
+        bytes_written += self.end_of_file(output)?;
+        self.bytes_written += bytes_written;
+        return Ok(bytes_written);
     }
 
     fn flush(&mut self, output: &mut dyn Write) -> std::io::Result<usize> {
+        // The variable-width stream is a single continuous run of bits, so there
+        // is nothing to pad mid-stream; the trailing partial byte is drained once
+        // at the very end (see `drain`). End markers are read before the padding
+        // bits are reached, so they never need a byte boundary.
+        if self.variable_width {
+            return Ok(0);
+        }
         // Flush any half written values
         match self.write_state {
             Some(_) => self.encode(NOOP, output),
@@ -192,21 +377,25 @@ impl Compressor {
         }
     }
 
-    fn end_of_file(&mut self, output: &mut dyn Write) -> std::io::Result<usize> {
+    // Write out any bits left in the variable-width buffer, zero padding the
+    // final byte. Only meaningful once, after the end marker has been emitted.
+    fn drain(&mut self, output: &mut dyn Write) -> std::io::Result<usize> {
         let mut bytes_written: usize = 0;
-        bytes_written += self.flush(output)?;
-        bytes_written += self.encode(EOF, output)?;
-        bytes_written += self.encode(EOF, output)?;
-        bytes_written += self.flush(output)?;
+        if self.variable_width && self.bit_count > 0 {
+            bytes_written += output.write(&[(self.bit_buffer & 0xFF) as u8])?;
+            self.bit_buffer = 0;
+            self.bit_count = 0;
+        }
         return Ok(bytes_written);
     }
 
-    fn end_of_stream(&mut self, output: &mut dyn Write) -> std::io::Result<usize> {
+    fn end_of_file(&mut self, output: &mut dyn Write) -> std::io::Result<usize> {
         let mut bytes_written: usize = 0;
         bytes_written += self.flush(output)?;
-        bytes_written += self.encode(EOS, output)?;
-        bytes_written += self.encode(EOS, output)?;
+        bytes_written += self.encode(EOF, output)?;
+        bytes_written += self.encode(EOF, output)?;
         bytes_written += self.flush(output)?;
+        bytes_written += self.drain(output)?;
         return Ok(bytes_written);
     }
 }
@@ -220,130 +409,236 @@ enum ReadState {
     EOS,
 }
 
+// A decoded dictionary entry: the code of its prefix, the first byte of the
+// reconstructed string, and the full string itself.
+#[derive(Debug)]
+struct Entry {
+    prefix: u16,
+    first: u8,
+    bytes: Vec<u8>,
+}
+
 #[derive(Debug)]
 struct Decompressor {
-    dict: Vec<Vec<u8>>,
-    key: Vec<u8>,
+    dict: Vec<Entry>,
+    prev: Option<u16>,
     read_state: ReadState,
+    // Variable-width bit reader state (only used when `variable_width` is set).
+    variable_width: bool,
+    bit_buffer: u32,
+    bit_count: u8,
+    // Must match the encoder's dictionary size so both sides stop adding
+    // entries at the same point (see the note on Config::max_dict_size).
+    max_dict_size: usize,
     debug: bool,
     verbose: bool,
 }
 
 impl Decompressor {
-    fn new() -> Decompressor {
+    fn with_config(config: &Config) -> Decompressor {
         let mut d = Decompressor {
-            dict: Vec::with_capacity(MAX_DICT_SIZE),
-            key: Vec::with_capacity(MAX_DICT_SIZE),
+            dict: Vec::with_capacity(config.max_dict_size),
+            prev: None,
             read_state: ReadState::Empty,
-            debug: false,
-            verbose: false,
+            variable_width: config.variable_width,
+            bit_buffer: 0,
+            bit_count: 0,
+            max_dict_size: config.max_dict_size,
+            debug: config.debug,
+            verbose: config.verbose,
         };
         d.flush_dictionary();
         return d;
     }
 
-    fn with_debug(debug: bool) -> Decompressor {
-        let mut d = Decompressor::new();
-        d.debug = debug;
-        return d;
+    fn with_mode(debug: bool, variable_width: bool) -> Decompressor {
+        return Decompressor::with_config(&Config::new().debug(debug).variable_width(variable_width));
     }
 
     fn print_dictionary(&self) {
-        print_dictionary(&self.dict);
+        eprintln!("Dictionary:");
+        for i in 0..self.dict.len() {
+            let e = &self.dict[i];
+            eprintln!("\t{:04}:\t(prefix {:04}) {:02X?}", i, e.prefix, &e.bytes);
+        }
     }
 
     fn flush_dictionary(&mut self) {
         self.dict.clear();
-        // Initialize dictionary
+        self.prev = None;
+        // Initialize dictionary with the single-byte literals.
         for n in 0..=255 {
-            self.dict.push(vec!(n));
+            self.dict.push(Entry { prefix: n, first: n as u8, bytes: vec!(n as u8) });
         }
     }
 
     fn decode(&mut self, value: u16, output: &mut dyn Write) -> std::io::Result<usize> {
         let mut bytes_written = 0;
 
-        let b: Vec<u8> = self.dict[value as usize].clone();
+        // Reconstruct the string for this code, handling the classic KwKwK
+        // case where the code is the very next one to be assigned and so is
+        // not yet present in the dictionary.
+        let b: Vec<u8> = if (value as usize) < self.dict.len() {
+            self.dict[value as usize].bytes.clone()
+        } else if value as usize == self.dict.len() {
+            // KwKwK is only valid once a previous code exists; a stream that
+            // opens with this code is malformed, so reject rather than panic.
+            let prev = match self.prev {
+                Some(prev) => prev as usize,
+                None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                    format!("invalid initial code {} (dictionary holds {} entries)", value, self.dict.len()))),
+            };
+            let mut s = self.dict[prev].bytes.clone();
+            s.push(self.dict[prev].first);
+            s
+        } else {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("invalid code {} (dictionary holds {} entries)", value, self.dict.len())));
+        };
+
         bytes_written += output.write(&b)?;
 
         if self.verbose {
-            debug!(self.debug, "Wrote: {:?}, Value: {:?}, Key: {:?}", &b, &value, &self.key);
+            debug!(self.debug, "Wrote: {:?}, Value: {:?}", &b, &value);
         }
 
-        self.key.extend(&b);
-
-        if self.key.len() > 1 && value < 256 {
-            // Single character following an extended sequence
-            // This should be a new entry in the dictionary
-            if self.verbose {
-                debug!(self.debug, "Adding {:?} as {:?}", &self.key, self.dict.len());
+        // Once we have a previous code, each new code adds one entry:
+        // previous string followed by the first byte of the current string.
+        if let Some(prev) = self.prev {
+            if self.dict.len() <= self.max_dict_size {
+                let mut s = self.dict[prev as usize].bytes.clone();
+                s.push(b[0]);
+                if self.verbose {
+                    debug!(self.debug, "Adding {:?} as {:?}", &s, self.dict.len());
+                }
+                self.dict.push(Entry { prefix: prev, first: s[0], bytes: s });
             }
-            self.dict.push(self.key.clone());
-            self.key.clear();
         }
 
+        self.prev = Some(value);
+
         return Ok(bytes_written);
     }
 
+    // Feed a single input byte to the decoder, writing any reconstructed bytes
+    // to `output` and returning (bytes written, end-of-stream reached). This is
+    // the per-byte core shared by `decompress` and the streaming reader.
+    fn decompress_byte(&mut self, b: u8, output: &mut dyn Write) -> std::io::Result<(usize, bool)> {
+        if self.variable_width {
+            return self.decompress_byte_var(b, output);
+        }
+
+        let mut bytes_written: usize = 0;
+        self.read_state = match self.read_state {
+            ReadState::Empty => ReadState::One(b),
+            ReadState::One(f) => ReadState::Two(f, b),
+            ReadState::Two(f,s) => {
+                let b = [f, s, b];
+                let first: u16 = u16::from_le_bytes([b[0], (b[1] >> 4)]);
+                let second: u16 = u16::from_le_bytes([((b[1] << 4) | b[2] >> 4), b[2] & 15]);
+                if self.verbose {
+                    debug!(self.debug, "Read: {:?}, Value: [{:?}, {:?}]", &b, &first, &second);
+                }
+                match first {
+                    NOOP => ReadState::Empty,
+                    EOF => ReadState::EOF,
+                    EOS => ReadState::EOS,
+                    _ => {
+                        if first == FLUSH_DICTIONARY {
+                            self.flush_dictionary();
+                        } else {
+                            bytes_written += self.decode(first, output)?;
+                        }
+                        match second {
+                            NOOP => ReadState::Empty,
+                            EOF => ReadState::EOF,
+                            EOS => ReadState::EOS,
+                            _ => {
+                                if second == FLUSH_DICTIONARY {
+                                    self.flush_dictionary();
+                                } else {
+                                    bytes_written += self.decode(second, output)?;
+                                }
+                                ReadState::Empty
+                            },
+                        }
+                    },
+                }
+            },
+            ReadState::EOF => ReadState::EOF,
+            ReadState::EOS => ReadState::EOS,
+        };
+        let done = matches!(self.read_state, ReadState::EOF | ReadState::EOS);
+        return Ok((bytes_written, done));
+    }
+
+    // The variable-width (bit packed) counterpart of decompress_byte. Reads as
+    // many whole codes as the accumulated bits allow, deriving the width from
+    // the decoder's own dictionary size so it follows the encoder's schedule
+    // (which is likewise driven by `next_code`, not a count of codes seen).
+    //
+    // `dict.len()` alone runs one insertion behind the encoder's `next_code`:
+    // the entry a decode() call adds is keyed by the *previous* code (`prev`)
+    // and the current string's first byte, i.e. it is the same entry the
+    // encoder added right after transmitting that previous code, not this
+    // one. So here we add the pending insertion back in when one is actually
+    // about to happen, to land on the same value the encoder used.
+    fn decompress_byte_var(&mut self, b: u8, output: &mut dyn Write) -> std::io::Result<(usize, bool)> {
+        let mut bytes_written: usize = 0;
+        self.bit_buffer |= (b as u32) << self.bit_count;
+        self.bit_count += 8;
+
+        loop {
+            let will_insert = self.prev.is_some() && self.dict.len() <= self.max_dict_size;
+            let next_code = self.dict.len() as u16 + if will_insert { 1 } else { 0 };
+            let mut width = code_width(next_code);
+            // A control code adds no entry, so the encoder writes it at the width
+            // of `dict.len()` alone. Where that width is one bit narrower, a value
+            // in its control slots can only be a control code, since every data
+            // code is at most `dict.len()` and so lies below those slots.
+            let narrow = code_width(self.dict.len() as u16);
+            if narrow < width && self.bit_count >= narrow
+                && self.bit_buffer & ((1u32 << narrow) - 1) >= (1u32 << narrow) - NUM_CONTROL as u32 {
+                width = narrow;
+            }
+            if (self.bit_count as u32) < width as u32 { break; }
+
+            let raw = (self.bit_buffer & ((1u32 << width) - 1)) as u16;
+            self.bit_buffer >>= width;
+            self.bit_count -= width;
+
+            // Map the top NUM_CONTROL slots back to the canonical control codes.
+            let code = if raw as u32 >= (1u32 << width) - NUM_CONTROL as u32 {
+                raw + (1u16 << MAX_CODE_WIDTH) - (1u16 << width)
+            } else {
+                raw
+            };
+            if self.verbose {
+                debug!(self.debug, "Read: {:?} at {:?} bits, Value: {:?}", &raw, &width, &code);
+            }
+
+            match code {
+                NOOP => {},
+                EOF => { self.read_state = ReadState::EOF; return Ok((bytes_written, true)); },
+                EOS => { self.read_state = ReadState::EOS; return Ok((bytes_written, true)); },
+                FLUSH_DICTIONARY => self.flush_dictionary(),
+                _ => {
+                    bytes_written += self.decode(code, output)?;
+                },
+            }
+        }
+        return Ok((bytes_written, false));
+    }
+
     fn decompress(&mut self, input: &mut dyn Read, output: &mut dyn Write) -> std::io::Result<(usize,usize)> {
         debug!(self.debug, "Decompressing");
         let mut bytes_read: usize = 0;
         let mut bytes_written: usize = 0;
         for byte in input.bytes() {
             bytes_read += 1;
-            let b = byte?;
-            self.read_state = match self.read_state {
-                ReadState::Empty => ReadState::One(b),
-                ReadState::One(f) => ReadState::Two(f, b),
-                ReadState::Two(f,s) => {
-                    let b = [f, s, b];
-                    let first: u16 = u16::from_le_bytes([b[0], (b[1] >> 4)]);
-                    let second: u16 = u16::from_le_bytes([((b[1] << 4) | b[2] >> 4), b[2] & 15]);
-                    if self.verbose {         
-                        debug!(self.debug, "Read: {:?}, Value: [{:?}, {:?}]", &b, &first, &second);
-                    }
-                    match first {
-                        NOOP => ReadState::Empty,
-                        EOF => ReadState::EOF,
-                        EOS => ReadState::EOS,
-                        _ => {
-                            if first == FLUSH_DICTIONARY {
-                                self.flush_dictionary();
-                            } else {
-                                bytes_written += self.decode(first, output)?;
-                            }
-                            match second {
-                                NOOP => ReadState::Empty,
-                                EOF => ReadState::EOF,
-                                EOS => ReadState::EOS,
-                                _ => {
-                                    if first == FLUSH_DICTIONARY {
-                                        self.flush_dictionary();
-                                    } else {
-                                        bytes_written += self.decode(second, output)?;
-                                    }
-                                    ReadState::Empty
-                                },
-                            }
-                        },
-                    }
-                },
-                ReadState::EOF => {
-                    debug!(self.debug, "End of File");
-                    debug!(self.debug, "------------------------------");
-                    ReadState::EOF
-                },
-                ReadState::EOS => {
-                    debug!(self.debug, "End of Stream");
-                    debug!(self.debug, "------------------------------");
-                    ReadState::EOS
-                }
-            };
-            match self.read_state {
-                ReadState::EOF => { break; },
-                ReadState::EOS => { break; },
-                _ => {},
-            }
+            let (w, done) = self.decompress_byte(byte?, output)?;
+            bytes_written += w;
+            if done { break; }
         }
         debug!(self.debug, "Done");
         debug!(self.debug, "------------------------------");
@@ -352,27 +647,570 @@ impl Decompressor {
 
 }
 
-// An implementation of LZW 12 bit fixed width compression
-pub fn compress(input: &mut dyn Read, output: &mut dyn Write, debug: bool) -> std::io::Result<(usize,usize)> {
-    let mut c = Compressor::with_debug(debug);
+// Frame format. A compressed stream starts with a small self-describing header
+// so that a reader can reject foreign or truncated input and recover the
+// parameters the stream was produced with.
+//
+//   offset  size  field
+//   0       4     magic number (FRAME_MAGIC)
+//   4       1     version (FRAME_VERSION)
+//   5       1     flags (see FLAG_* below)
+//   6       8     uncompressed length, little endian (only if FLAG_LENGTH)
+//   ...     ..    LZW body
+//   end     4     content checksum, little endian (only if FLAG_CHECKSUM)
+pub const FRAME_MAGIC: [u8;4] = *b"LZW1";
+pub const FRAME_VERSION: u8 = 1;
+pub const FLAG_VARIABLE_WIDTH: u8 = 0b0000_0001;
+pub const FLAG_CHECKSUM: u8 = 0b0000_0010;
+pub const FLAG_LENGTH: u8 = 0b0000_0100;
+pub const FLAG_BLOCKS: u8 = 0b0000_1000;
+// Set when a 4-byte little-endian compressed-body length is written just before
+// the LZW body. This makes a body that does not end on a byte boundary (the
+// variable-width bit stream) self-delimiting, so the reader realigns before the
+// trailing checksum.
+pub const FLAG_BODY_LENGTH: u8 = 0b0001_0000;
+
+// Default block size for block mode (see compress_blocks). Each block is
+// compressed independently with its own flushed dictionary.
+pub const DEFAULT_BLOCK_SIZE: usize = 256 * 1024;
+
+// A dependency-free Adler-32 checksum over the original (uncompressed) bytes.
+struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    fn new() -> Adler32 {
+        return Adler32 { a: 1, b: 0 };
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &x in bytes {
+            self.a = (self.a + x as u32) % 65521;
+            self.b = (self.b + self.a) % 65521;
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        return (self.b << 16) | self.a;
+    }
+}
+
+// Wraps a reader and checksums every byte that passes through it, so the
+// checksum is computed over the original bytes as they are fed to the encoder.
+struct HashingReader<'a> {
+    inner: &'a mut dyn Read,
+    hash: Adler32,
+}
+
+impl Read for HashingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hash.update(&buf[..n]);
+        return Ok(n);
+    }
+}
+
+// Wraps a writer and checksums every byte that passes through it, so the
+// checksum is computed over the bytes the decoder reconstructs.
+struct HashingWriter<'a> {
+    inner: &'a mut dyn Write,
+    hash: Adler32,
+}
+
+impl Write for HashingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hash.update(&buf[..n]);
+        return Ok(n);
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+// Runs the LZW encoder over `input`, writing the raw body (no frame) to `output`.
+fn compress_body(input: &mut dyn Read, output: &mut dyn Write, config: &Config) -> std::io::Result<(usize,usize)> {
+    let mut c = Compressor::with_config(config);
     let (r, mut w) = c.compress(input, output)?;
     w += c.end_of_file(output)?;
-    if debug {
+    if config.debug {
         c.print_dictionary();
     }
     return Ok((r,w));
 }
 
-// An implementation of LZW 12 bit fixed width decompression
-pub fn decompress(input: &mut dyn Read, output: &mut dyn Write, debug: bool) -> std::io::Result<(usize,usize)> {
-    let mut d = Decompressor::with_debug(debug);
+// Runs the LZW decoder over the raw body in `input`, writing to `output`.
+fn decompress_body(input: &mut dyn Read, output: &mut dyn Write, config: &Config) -> std::io::Result<(usize,usize)> {
+    let mut d = Decompressor::with_config(config);
     let (r,w) = d.decompress(input, output)?;
-    if debug {
+    if config.debug {
         d.print_dictionary();
     }
     return Ok((r,w));
 }
 
+// Reads exactly `len` bytes of a frame-declared body. The buffer grows as the
+// bytes actually arrive (via `take` + `read_to_end`) instead of pre-allocating
+// the declared size, so a crafted length cannot force a huge up-front
+// allocation; a short read means the frame was truncated.
+fn read_frame_bytes(input: &mut dyn Read, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut body: Vec<u8> = Vec::new();
+    let got = input.take(len as u64).read_to_end(&mut body)?;
+    if got != len {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof,
+            format!("truncated frame: expected {} body bytes, got {}", len, got)));
+    }
+    return Ok(body);
+}
+
+// Compresses `input` into a self-describing frame on `output` using fixed
+// 12-bit codes and a trailing Adler-32 checksum over the original bytes.
+pub fn compress(input: &mut dyn Read, output: &mut dyn Write, debug: bool) -> std::io::Result<(usize,usize)> {
+    return compress_with(input, output, &Config::new().debug(debug));
+}
+
+// Like compress, but using variable-width (9..12 bit) codes.
+pub fn compress_variable(input: &mut dyn Read, output: &mut dyn Write, debug: bool) -> std::io::Result<(usize,usize)> {
+    return compress_with(input, output, &Config::new().debug(debug).variable_width(true));
+}
+
+// Compresses `input` into a frame using the supplied `Config`, letting callers
+// tune the dictionary size and the adaptive-flush thresholds.
+pub fn compress_with(input: &mut dyn Read, output: &mut dyn Write, config: &Config) -> std::io::Result<(usize,usize)> {
+    let mut flags: u8 = FLAG_CHECKSUM;
+    if config.variable_width {
+        // The variable-width bit stream does not end on a byte boundary, so the
+        // body is length-prefixed to keep the trailing checksum aligned.
+        flags |= FLAG_VARIABLE_WIDTH | FLAG_BODY_LENGTH;
+    }
+
+    let mut bytes_written: usize = 0;
+    bytes_written += output.write(&FRAME_MAGIC)?;
+    bytes_written += output.write(&[FRAME_VERSION, flags])?;
+
+    // Checksum the original bytes as the encoder consumes them.
+    let mut hr = HashingReader { inner: input, hash: Adler32::new() };
+    let bytes_read = if flags & FLAG_BODY_LENGTH != 0 {
+        // Buffer the body so its byte length can be recorded ahead of it.
+        let mut body_buf: Vec<u8> = Vec::new();
+        let (r, _) = compress_body(&mut hr, &mut body_buf, config)?;
+        bytes_written += output.write(&(body_buf.len() as u32).to_le_bytes())?;
+        bytes_written += output.write(&body_buf)?;
+        r
+    } else {
+        let (r, body) = compress_body(&mut hr, output, config)?;
+        bytes_written += body;
+        r
+    };
+
+    let checksum = hr.hash.finish();
+    bytes_written += output.write(&checksum.to_le_bytes())?;
+
+    return Ok((bytes_read, bytes_written));
+}
+
+// Reads a frame written by compress/compress_variable, validating the magic and
+// version, configuring the decoder from the flags, and verifying the trailing
+// checksum against the reconstructed bytes.
+pub fn decompress(input: &mut dyn Read, output: &mut dyn Write, debug: bool) -> std::io::Result<(usize,usize)> {
+    return decompress_with(input, output, &Config::new().debug(debug));
+}
+
+// Like decompress, but using the supplied `Config`. The code-width mode is
+// still taken from the frame flags; the other knobs (dictionary size, debug)
+// come from `config`, and the dictionary size must match the one used to
+// compress the stream.
+pub fn decompress_with(input: &mut dyn Read, output: &mut dyn Write, config: &Config) -> std::io::Result<(usize,usize)> {
+    let mut header = [0u8; 6];
+    input.read_exact(&mut header)?;
+    if header[0..4] != FRAME_MAGIC {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad magic number"));
+    }
+    let version = header[4];
+    if version != FRAME_VERSION {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+            format!("unsupported frame version {}", version)));
+    }
+    let flags = header[5];
+
+    let mut bytes_read: usize = header.len();
+    if flags & FLAG_LENGTH != 0 {
+        // Declared length is advisory; read past it.
+        let mut len = [0u8; 8];
+        input.read_exact(&mut len)?;
+        bytes_read += len.len();
+    }
+
+    // The code-width mode travels in the frame, so it overrides whatever the
+    // caller's config carried; the remaining knobs come from `config`.
+    let cfg = config.clone().variable_width(flags & FLAG_VARIABLE_WIDTH != 0);
+
+    if flags & FLAG_BLOCKS != 0 {
+        return decompress_blocks(input, output, &cfg, flags, bytes_read);
+    }
+
+    // Checksum the bytes as they are reconstructed.
+    let mut hw = HashingWriter { inner: output, hash: Adler32::new() };
+    let bytes_written = if flags & FLAG_BODY_LENGTH != 0 {
+        // Read exactly the recorded body length so the reader realigns onto the
+        // trailing checksum even when the body does not end on a byte boundary.
+        let mut len = [0u8; 4];
+        input.read_exact(&mut len)?;
+        bytes_read += len.len();
+        let body_len = u32::from_le_bytes(len) as usize;
+        let body = read_frame_bytes(input, body_len)?;
+        bytes_read += body_len;
+        let mut reader: &[u8] = &body;
+        let (_, w) = decompress_body(&mut reader, &mut hw, &cfg)?;
+        w
+    } else {
+        let (body, w) = decompress_body(input, &mut hw, &cfg)?;
+        bytes_read += body;
+        w
+    };
+    let actual = hw.hash.finish();
+
+    if flags & FLAG_CHECKSUM != 0 {
+        let mut stored = [0u8; 4];
+        input.read_exact(&mut stored)?;
+        bytes_read += stored.len();
+        let expected = u32::from_le_bytes(stored);
+        if actual != expected {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("checksum mismatch: expected {:08X}, computed {:08X}", expected, actual)));
+        }
+    }
+
+    return Ok((bytes_read, bytes_written));
+}
+
+// Returns a sensible default worker-thread count, saturating to 1 if the
+// platform cannot report its available parallelism.
+fn default_threads() -> usize {
+    return std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+}
+
+// Compresses a single block into a raw, self-terminating LZW body (it carries
+// its own EOF marker), with a freshly flushed dictionary.
+fn compress_block(data: &[u8], config: &Config) -> std::io::Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::new();
+    let mut reader: &[u8] = data;
+    compress_body(&mut reader, &mut out, config)?;
+    return Ok(out);
+}
+
+// Decompresses a single raw LZW body (as produced by compress_block).
+fn decompress_block(data: &[u8], config: &Config) -> std::io::Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::new();
+    let mut reader: &[u8] = data;
+    decompress_body(&mut reader, &mut out, config)?;
+    return Ok(out);
+}
+
+// Applies `f` to each block across up to `threads` worker threads, returning
+// the results in block order. Because the blocks are independent, workers pull
+// the next unclaimed index off a shared counter; the first error encountered is
+// propagated. `f` is identical on every worker, so the only nondeterminism is
+// which thread handles which block.
+fn map_blocks<T, F>(blocks: &[&[u8]], threads: usize, f: F) -> std::io::Result<Vec<T>>
+where
+    T: Send,
+    F: Fn(&[u8]) -> std::io::Result<T> + Sync,
+{
+    let n = blocks.len();
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+    let threads = threads.max(1).min(n);
+    let mut slots: Vec<Option<std::io::Result<T>>> = (0..n).map(|_| None).collect();
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    {
+        let next = &next;
+        let f = &f;
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads).map(|_| {
+                scope.spawn(move || {
+                    let mut local: Vec<(usize, std::io::Result<T>)> = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        if i >= blocks.len() { break; }
+                        local.push((i, f(blocks[i])));
+                    }
+                    return local;
+                })
+            }).collect();
+            for h in handles {
+                for (i, res) in h.join().unwrap() {
+                    slots[i] = Some(res);
+                }
+            }
+        });
+    }
+    // Propagate the first error, else collect the outputs in block order.
+    return slots.into_iter().map(|s| s.unwrap()).collect();
+}
+
+// Like compress_blocks_with, but using a plain default Config built from
+// `debug` and `variable_width` (see compress/compress_with for the analogous
+// pair on the non-block path).
+pub fn compress_blocks(input: &mut dyn Read, output: &mut dyn Write, debug: bool, variable_width: bool, block_size: usize, threads: usize) -> std::io::Result<(usize,usize)> {
+    let config = Config::new().debug(debug).variable_width(variable_width);
+    return compress_blocks_with(input, output, &config, block_size, threads);
+}
+
+// Compresses `input` into a block-mode frame using the supplied `Config`, so
+// the dictionary size and adaptive-flush thresholds can be tuned for block
+// mode too, not just the non-block path (decompress_blocks already takes a
+// full `Config`; this is its compression-side counterpart). The input is
+// split into `block_size` chunks, each compressed independently with its own
+// flushed dictionary, so blocks can be (de)compressed in parallel at a small
+// cost in ratio. `threads` worker threads compress the blocks concurrently
+// and their bodies are concatenated in order. Passing 0 for either knob
+// selects a default (DEFAULT_BLOCK_SIZE / the platform parallelism).
+//
+// The frame records each block's compressed length so a reader can split the
+// body back into independent blocks:
+//
+//   ... header (+ optional length) ...
+//   4     block size, little endian (advisory)
+//   4     block count, little endian
+//   4*N   per-block compressed length, little endian
+//   ...   the N block bodies, concatenated in order
+//   4     content checksum over the original bytes (if FLAG_CHECKSUM)
+pub fn compress_blocks_with(input: &mut dyn Read, output: &mut dyn Write, config: &Config, block_size: usize, threads: usize) -> std::io::Result<(usize,usize)> {
+    let block_size = if block_size == 0 { DEFAULT_BLOCK_SIZE } else { block_size };
+    let threads = if threads == 0 { default_threads() } else { threads };
+
+    // Block mode needs to split the input into independent chunks, so unlike the
+    // streaming path the whole input is read up front.
+    let mut data: Vec<u8> = Vec::new();
+    input.read_to_end(&mut data)?;
+    let bytes_read = data.len();
+
+    let blocks: Vec<&[u8]> = if data.is_empty() {
+        Vec::new()
+    } else {
+        data.chunks(block_size).collect()
+    };
+    let bodies = map_blocks(&blocks, threads, |b| compress_block(b, config))?;
+
+    // Block mode reads the whole input up front, so the uncompressed length is
+    // known and recorded as the optional declared length.
+    let mut flags: u8 = FLAG_CHECKSUM | FLAG_BLOCKS | FLAG_LENGTH;
+    if config.variable_width {
+        flags |= FLAG_VARIABLE_WIDTH;
+    }
+
+    let mut bytes_written: usize = 0;
+    bytes_written += output.write(&FRAME_MAGIC)?;
+    bytes_written += output.write(&[FRAME_VERSION, flags])?;
+    bytes_written += output.write(&(bytes_read as u64).to_le_bytes())?;
+    bytes_written += output.write(&(block_size as u32).to_le_bytes())?;
+    bytes_written += output.write(&(bodies.len() as u32).to_le_bytes())?;
+    for body in &bodies {
+        bytes_written += output.write(&(body.len() as u32).to_le_bytes())?;
+    }
+
+    let mut hash = Adler32::new();
+    hash.update(&data);
+    for body in &bodies {
+        bytes_written += output.write(body)?;
+    }
+    bytes_written += output.write(&hash.finish().to_le_bytes())?;
+
+    return Ok((bytes_read, bytes_written));
+}
+
+// Reads the block-mode body of a frame (invoked by decompress once FLAG_BLOCKS
+// is seen). The per-block compressed lengths let the bodies be sliced apart and
+// decompressed independently; the decoded blocks are written out in order and
+// checked against the trailing content checksum.
+fn decompress_blocks(input: &mut dyn Read, output: &mut dyn Write, config: &Config, flags: u8, mut bytes_read: usize) -> std::io::Result<(usize,usize)> {
+    let mut buf4 = [0u8; 4];
+    input.read_exact(&mut buf4)?; // block size (advisory)
+    bytes_read += buf4.len();
+    input.read_exact(&mut buf4)?;
+    bytes_read += buf4.len();
+    let count = u32::from_le_bytes(buf4) as usize;
+
+    // Grow these as the (attacker-controlled) count is consumed rather than
+    // pre-reserving it, so a crafted count cannot force a huge up-front alloc;
+    // a truncated table fails fast on the first short read_exact below.
+    let mut lengths: Vec<usize> = Vec::new();
+    for _ in 0..count {
+        input.read_exact(&mut buf4)?;
+        bytes_read += buf4.len();
+        lengths.push(u32::from_le_bytes(buf4) as usize);
+    }
+
+    let mut bodies: Vec<Vec<u8>> = Vec::new();
+    for len in &lengths {
+        bodies.push(read_frame_bytes(input, *len)?);
+        bytes_read += *len;
+    }
+
+    let refs: Vec<&[u8]> = bodies.iter().map(|b| b.as_slice()).collect();
+    let decoded = map_blocks(&refs, default_threads(), |b| decompress_block(b, config))?;
+
+    // Checksum the reconstructed bytes as they are emitted in block order.
+    let mut hw = HashingWriter { inner: output, hash: Adler32::new() };
+    let mut bytes_written: usize = 0;
+    for chunk in &decoded {
+        hw.write_all(chunk)?;
+        bytes_written += chunk.len();
+    }
+    let actual = hw.hash.finish();
+
+    if flags & FLAG_CHECKSUM != 0 {
+        let mut stored = [0u8; 4];
+        input.read_exact(&mut stored)?;
+        bytes_read += stored.len();
+        let expected = u32::from_le_bytes(stored);
+        if actual != expected {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("checksum mismatch: expected {:08X}, computed {:08X}", expected, actual)));
+        }
+    }
+
+    return Ok((bytes_read, bytes_written));
+}
+
+// A Write adapter that LZW-compresses everything written to it and forwards the
+// encoded bytes to an inner writer. Bytes are fed to the encoder one at a time,
+// so a partially matched key is carried across write calls. The final code and
+// the EOF marker are emitted by `finish` or when the writer is dropped; `flush`
+// only flushes the inner writer and leaves the stream open to further writes.
+//
+// This operates on the raw LZW body (no frame); pair it with DecompressReader.
+pub struct CompressWriter<W: Write> {
+    // Held in an Option so `finish` can move the writer out even though the
+    // type has a Drop impl (a bare field move would be an E0509 error). It is
+    // always Some until `finish` takes it.
+    inner: Option<W>,
+    compressor: Compressor,
+    finished: bool,
+}
+
+impl<W: Write> CompressWriter<W> {
+    pub fn new(inner: W) -> CompressWriter<W> {
+        return CompressWriter::with_mode(inner, false, false);
+    }
+
+    pub fn with_mode(inner: W, debug: bool, variable_width: bool) -> CompressWriter<W> {
+        return CompressWriter {
+            inner: Some(inner),
+            compressor: Compressor::with_mode(debug, variable_width),
+            finished: false,
+        };
+    }
+
+    // Finalize the stream (emit the trailing code and EOF) and return the inner
+    // writer. Useful when the caller needs the writer back or wants to observe
+    // I/O errors that Drop would otherwise swallow.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.finalize()?;
+        // Take the inner writer out. `self` still has a Drop impl, but `inner`
+        // is now None so Drop's finalize becomes a no-op.
+        return Ok(self.inner.take().expect("inner writer already taken"));
+    }
+
+    fn finalize(&mut self) -> std::io::Result<()> {
+        if !self.finished {
+            if let Some(inner) = self.inner.as_mut() {
+                self.compressor.finish(inner)?;
+            }
+            self.finished = true;
+        }
+        return Ok(());
+    }
+}
+
+impl<W: Write> Write for CompressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let inner = self.inner.as_mut().expect("write after finish");
+        for &b in buf {
+            self.compressor.compress_byte(b, inner)?;
+        }
+        return Ok(buf.len());
+    }
+
+    // Flushes the inner writer without ending the stream, so the compressor can
+    // keep matching across the flush (the LZW code boundaries do not have to
+    // line up with flush calls). The final code and EOF are emitted only by
+    // `finish`/`Drop`.
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for CompressWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finalize();
+    }
+}
+
+// A Read adapter that lazily LZW-decompresses from an inner reader. Input is
+// pulled and decoded only as the caller consumes output, so back-pressure from
+// a small `buf` bounds how far ahead the decoder runs.
+//
+// This operates on the raw LZW body (no frame); pair it with CompressWriter.
+pub struct DecompressReader<R: Read> {
+    inner: R,
+    decompressor: Decompressor,
+    buffer: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> DecompressReader<R> {
+    pub fn new(inner: R) -> DecompressReader<R> {
+        return DecompressReader::with_mode(inner, false, false);
+    }
+
+    pub fn with_mode(inner: R, debug: bool, variable_width: bool) -> DecompressReader<R> {
+        return DecompressReader {
+            inner,
+            decompressor: Decompressor::with_mode(debug, variable_width),
+            buffer: Vec::new(),
+            pos: 0,
+            done: false,
+        };
+    }
+}
+
+impl<R: Read> Read for DecompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Refill the decoded buffer one input byte at a time until it holds some
+        // output or the stream ends. A single input byte may decode to zero
+        // bytes (mid-code) or to several, so we loop rather than assume a ratio.
+        while self.pos >= self.buffer.len() && !self.done {
+            self.buffer.clear();
+            self.pos = 0;
+            let mut byte = [0u8; 1];
+            let n = self.inner.read(&mut byte)?;
+            if n == 0 {
+                self.done = true;
+                break;
+            }
+            let (_written, done) = self.decompressor.decompress_byte(byte[0], &mut self.buffer)?;
+            if done {
+                self.done = true;
+            }
+        }
+
+        let available = self.buffer.len() - self.pos;
+        let n = available.min(buf.len());
+        buf[..n].copy_from_slice(&self.buffer[self.pos..self.pos + n]);
+        self.pos += n;
+        return Ok(n);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -383,76 +1221,293 @@ mod tests {
         consectetur nisi.".as_bytes().to_vec()
     }
 
-    fn compressed() -> Vec<u8> { 
-        vec![
-            76, 6, 240, 114, 6, 80, 109, 2, 0, 105, 
-            7, 0, 115, 7, 80, 2, 22, 64, 111, 6, 
-            192, 111, 7, 32, 32, 7, 48, 105, 7, 64, 
-            32, 6, 16, 109, 6, 80, 116, 2, 192, 32, 
-            6, 48, 111, 6, 224, 115, 6, 80, 99, 7, 
-            64, 101, 7, 64, 117, 7, 32, 10, 22, 64, 
-            3, 22, 144, 115, 6, 48, 105, 6, 224, 103, 
-            2, 0, 101, 6, 192, 9, 18, 224, 10, 2, 
-            0, 32, 2, 0, 27, 18, 0, 27, 21, 96, 
-            101, 7, 48, 116, 6, 144, 98, 7, 80, 108, 
-            7, 80, 2, 22, 144, 112, 7, 48, 117, 6, 
-            208, 32, 6, 224, 117, 6, 192, 108, 6, 16, 
-            44, 2, 0, 112, 7, 32, 17, 22, 144, 36, 
-            18, 0, 97, 7, 64, 32, 6, 192, 101, 6, 
-            240, 8, 22, 80, 100, 2, 192, 13, 22, 240, 
-            110, 6, 64, 105, 6, 208, 101, 6, 224, 116, 
-            7, 80, 109, 0, 160, 28, 18, 0, 55, 22, 
-            48, 14, 23, 48, 101, 6, 48, 116, 6, 80, 
-            53, 23, 32, 37, 22, 144, 115, 6, 144, 46, 
-            15, 191, 254, 255, 239
-        ]
-     }
+    // Compresses `input`, decompresses the result, and asserts the round trip
+    // reproduces the original bytes exactly.
+    fn round_trip(input: &[u8]) -> std::io::Result<()> {
+        let mut reader: &[u8] = input;
+        let mut compressed: Vec<u8> = Vec::new();
+        let (r, w) = compress(&mut reader, &mut compressed, true)?;
+        let ratio = compression_ratio(r, w);
+        eprintln!("[Compress] Bytes Read: {}, Bytes Written: {}, Compression Ratio: {:3} %", r, w, ratio);
+
+        let mut reader: &[u8] = &compressed;
+        let mut output: Vec<u8> = Vec::new();
+        let (r, w) = decompress(&mut reader, &mut output, true)?;
+        eprintln!("[Decompress] Bytes Read: {}, Bytes Written: {}", r, w);
+        assert_eq!(input.to_vec(), output);
+        return Ok(());
+    }
 
      #[test]
-     fn test_simple_compress() -> std::io::Result<()> {
-         let mut input: &[u8] = "          ".as_bytes();
-         let expected: Vec<u8> = vec![32, 2, 0, 0, 18, 0, 1, 18, 0, 32, 15, 191, 254, 255, 239];
-         let mut output: Vec<u8> = Vec::new();
-         let (r, w) = compress(&mut input, &mut output, true)?;
-         let ratio = compression_ratio(r, w);
-         eprintln!("[Compress] Bytes Read: {}, Bytes Written: {}, Compression Ratio: {:3} %", r, w, ratio);
-         assert_eq!(expected, output);
-         return Ok(());
+     fn test_simple_round_trip() -> std::io::Result<()> {
+         return round_trip("          ".as_bytes());
      }
 
     #[test]
-    fn test_simple_decompress() -> std::io::Result<()> {
-        let mut input: &[u8] = &vec![32, 2, 0, 0, 18, 0, 1, 18, 0, 32, 15, 191, 254, 255, 239];
-        let expected: Vec<u8> = "          ".as_bytes().to_vec();
+    fn test_round_trip() -> std::io::Result<()> {
+        return round_trip(&uncompressed());
+    }
+
+    // Exercises the KwKwK edge case, where a code equal to the next code to be
+    // assigned is emitted (e.g. a run that keeps extending a repeated pattern).
+    #[test]
+    fn test_round_trip_repeated() -> std::io::Result<()> {
+        let input: Vec<u8> = "ababababababababab".as_bytes().to_vec();
+        return round_trip(&input);
+    }
+
+    // Same as round_trip, but through the variable-width (9..12 bit) codec.
+    fn round_trip_variable(input: &[u8]) -> std::io::Result<()> {
+        let mut reader: &[u8] = input;
+        let mut compressed: Vec<u8> = Vec::new();
+        let (r, w) = compress_variable(&mut reader, &mut compressed, true)?;
+        let ratio = compression_ratio(r, w);
+        eprintln!("[Compress] Bytes Read: {}, Bytes Written: {}, Compression Ratio: {:3} %", r, w, ratio);
+
+        let mut reader: &[u8] = &compressed;
+        let mut output: Vec<u8> = Vec::new();
+        let (r, w) = decompress(&mut reader, &mut output, true)?;
+        eprintln!("[Decompress] Bytes Read: {}, Bytes Written: {}", r, w);
+        assert_eq!(input.to_vec(), output);
+        return Ok(());
+    }
+
+    #[test]
+    fn test_variable_round_trip() -> std::io::Result<()> {
+        return round_trip_variable(&uncompressed());
+    }
+
+    #[test]
+    fn test_variable_round_trip_simple() -> std::io::Result<()> {
+        return round_trip_variable("          ".as_bytes());
+    }
+
+    // Regression test: high-entropy input never pushes the running ratio past
+    // the 200% adaptive flush threshold, so the dictionary fills up and the
+    // variable-width schedule (driven by `next_code`) has to stay in step over
+    // a very long run of codes at the full width. A simple LCG stands in for a
+    // PRNG so the test stays deterministic.
+    #[test]
+    fn test_variable_round_trip_incompressible() -> std::io::Result<()> {
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        let input: Vec<u8> = (0..70_000).map(|_| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 56) as u8
+        }).collect();
+        return round_trip_variable(&input);
+    }
+
+    // Regression test: once a capped dictionary (via Config::max_dict_size)
+    // stops growing, the variable-width schedule must stop growing with it
+    // instead of continuing to widen every code for the rest of the stream.
+    #[test]
+    fn test_variable_round_trip_capped_dict() -> std::io::Result<()> {
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        let input: Vec<u8> = (0..20_000).map(|_| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 56) as u8
+        }).collect();
+        let config = Config::new().variable_width(true).max_dict_size(300).disable_flushing();
+
+        let mut compressed: Vec<u8> = Vec::new();
+        let (_r, w) = compress_with(&mut &input[..], &mut compressed, &config)?;
+        // Every real code in use is at most 300, well under the 9-bit limit of
+        // 506, so 9 bits per code always
+        // suffices (roughly input.len() * 9 / 8 bytes, since incompressible
+        // data emits close to one code per input byte). A schedule that keeps
+        // widening past 9 bits once the dictionary caps out would instead
+        // trend toward input.len() * 12 / 8.
+        let nine_bit_upper_bound = input.len() * 9 / 8 + 64;
+        assert!(w < nine_bit_upper_bound,
+            "output {} bytes exceeds the 9-bit-only upper bound of {} — width grew past the capped dictionary's actual size",
+            w, nine_bit_upper_bound);
+
+        let mut output: Vec<u8> = Vec::new();
+        let (_r, _w) = decompress_with(&mut &compressed[..], &mut output, &config)?;
+        assert_eq!(input, output);
+        return Ok(());
+    }
+
+    // A stream that does not start with the frame magic must be rejected.
+    #[test]
+    fn test_reject_foreign_input() {
+        let mut input: &[u8] = b"not an lzw frame at all";
+        let mut output: Vec<u8> = Vec::new();
+        assert!(decompress(&mut input, &mut output, false).is_err());
+    }
+
+    // The streaming Write/Read adapters round trip through the raw LZW body,
+    // across many small write/read calls.
+    #[test]
+    fn test_stream_adapters() -> std::io::Result<()> {
+        use std::io::{Read, Write};
+
+        let input = uncompressed();
+        let mut compressed: Vec<u8> = Vec::new();
+        {
+            let mut writer = CompressWriter::new(&mut compressed);
+            // Write in small chunks to exercise keys spanning write calls.
+            for chunk in input.chunks(7) {
+                writer.write_all(chunk)?;
+            }
+            writer.flush()?;
+        }
+
+        let mut reader = DecompressReader::new(&compressed[..]);
         let mut output: Vec<u8> = Vec::new();
-        let (r, w) = decompress(&mut input, &mut output, true)?;
-        let ratio = compression_ratio(w, r) as u64;
-        eprintln!("[Decompress] Bytes Read: {}, Bytes Written: {}, Compression Ratio: {:3} %", r, w, ratio);
-        assert_eq!(expected, output);
+        // Read in small chunks to exercise output back-pressure.
+        let mut chunk = [0u8; 5];
+        loop {
+            let n = reader.read(&mut chunk)?;
+            if n == 0 { break; }
+            output.extend_from_slice(&chunk[..n]);
+        }
+
+        assert_eq!(input, output);
         return Ok(());
     }
 
+    // Corrupting a body byte must be caught by the trailing checksum.
     #[test]
-    fn test_compress() -> std::io::Result<()> {
-        let mut input: &[u8] = &uncompressed();
-        let expected: Vec<u8> = compressed();
+    fn test_reject_corrupted_input() -> std::io::Result<()> {
+        let mut reader: &[u8] = &uncompressed();
+        let mut compressed: Vec<u8> = Vec::new();
+        compress(&mut reader, &mut compressed, false)?;
+
+        // Invert every bit of a byte in the middle of the body (past the 6 byte
+        // header).
+        let mid = compressed.len() / 2;
+        compressed[mid] ^= 0xFF;
+
+        let mut reader: &[u8] = &compressed;
         let mut output: Vec<u8> = Vec::new();
-        let (r, w) = compress(&mut input, &mut output, true)?;
-        let ratio = compression_ratio(r, w) as u64;
+        assert!(decompress(&mut reader, &mut output, false).is_err());
+        return Ok(());
+    }
+
+    // Round trips through the block-mode codec with a small block size so the
+    // input spans several independently compressed blocks.
+    fn round_trip_blocks(input: &[u8], block_size: usize, threads: usize) -> std::io::Result<()> {
+        let mut reader: &[u8] = input;
+        let mut compressed: Vec<u8> = Vec::new();
+        let (r, w) = compress_blocks(&mut reader, &mut compressed, true, false, block_size, threads)?;
+        let ratio = compression_ratio(r, w);
         eprintln!("[Compress] Bytes Read: {}, Bytes Written: {}, Compression Ratio: {:3} %", r, w, ratio);
-        assert_eq!(expected, output);
+
+        let mut reader: &[u8] = &compressed;
+        let mut output: Vec<u8> = Vec::new();
+        let (r, w) = decompress(&mut reader, &mut output, true)?;
+        eprintln!("[Decompress] Bytes Read: {}, Bytes Written: {}", r, w);
+        assert_eq!(input.to_vec(), output);
+        return Ok(());
+    }
+
+    #[test]
+    fn test_block_round_trip() -> std::io::Result<()> {
+        return round_trip_blocks(&uncompressed(), 32, 2);
+    }
+
+    // Round trips through the Config entry points, with adaptive flushing
+    // disabled and a reduced dictionary size, to exercise the tunable knobs.
+    #[test]
+    fn test_config_round_trip() -> std::io::Result<()> {
+        let config = Config::new().debug(true).disable_flushing().max_dict_size(1024);
+
+        let input = uncompressed();
+        let mut reader: &[u8] = &input;
+        let mut compressed: Vec<u8> = Vec::new();
+        compress_with(&mut reader, &mut compressed, &config)?;
+
+        let mut reader: &[u8] = &compressed;
+        let mut output: Vec<u8> = Vec::new();
+        decompress_with(&mut reader, &mut output, &config)?;
+        assert_eq!(input, output);
+        return Ok(());
+    }
+
+    // Block mode must also honor a tuned Config (not just the non-block
+    // path), so round trip through compress_blocks_with with a reduced
+    // dictionary size and adaptive flushing disabled.
+    #[test]
+    fn test_block_config_round_trip() -> std::io::Result<()> {
+        let config = Config::new().disable_flushing().max_dict_size(512);
+
+        let input = uncompressed();
+        let mut reader: &[u8] = &input;
+        let mut compressed: Vec<u8> = Vec::new();
+        compress_blocks_with(&mut reader, &mut compressed, &config, 32, 2)?;
+
+        let mut reader: &[u8] = &compressed;
+        let mut output: Vec<u8> = Vec::new();
+        decompress_with(&mut reader, &mut output, &config)?;
+        assert_eq!(input, output);
         return Ok(());
     }
 
+    // Sizes outside 1..=MAX_DICT_SIZE must be clamped rather than accepted
+    // as-is: above MAX_DICT_SIZE they collide with the reserved control
+    // codes, and at or beyond the 12-bit ceiling they overflow `next_code`.
+    #[test]
+    fn test_max_dict_size_clamped() {
+        assert_eq!(Config::new().max_dict_size(0).max_dict_size, 1);
+        assert_eq!(Config::new().max_dict_size(5000).max_dict_size, MAX_DICT_SIZE);
+        assert_eq!(Config::new().max_dict_size(70_000).max_dict_size, MAX_DICT_SIZE);
+    }
+
+    // A frame whose first body code is the very next code to be assigned (256)
+    // is a malformed KwKwK with no previous code: it must be rejected, not
+    // panic. The three body bytes pack the codes [256, EOF] in fixed width.
+    #[test]
+    fn test_reject_kwkwk_without_previous() {
+        let mut frame: Vec<u8> = Vec::new();
+        frame.extend_from_slice(&FRAME_MAGIC);
+        frame.extend_from_slice(&[FRAME_VERSION, FLAG_CHECKSUM]);
+        frame.extend_from_slice(&[0x00, 0x1F, 0xEF]);
+
+        let mut input: &[u8] = &frame;
+        let mut output: Vec<u8> = Vec::new();
+        assert!(decompress(&mut input, &mut output, false).is_err());
+    }
+
+    // A block frame claiming an absurd body length must error out instead of
+    // trying to allocate it up front.
     #[test]
-    fn test_decompress() -> std::io::Result<()> {
-        let mut input: &[u8] = &compressed();
-        let expected: Vec<u8> = uncompressed();
+    fn test_reject_oversized_body_length() {
+        let mut frame: Vec<u8> = Vec::new();
+        frame.extend_from_slice(&FRAME_MAGIC);
+        frame.extend_from_slice(&[FRAME_VERSION, FLAG_CHECKSUM | FLAG_BLOCKS | FLAG_LENGTH]);
+        frame.extend_from_slice(&0u64.to_le_bytes()); // declared length
+        frame.extend_from_slice(&0u32.to_le_bytes()); // block size
+        frame.extend_from_slice(&1u32.to_le_bytes()); // one block
+        frame.extend_from_slice(&u32::MAX.to_le_bytes()); // crafted block length
+        // ...followed by only a handful of bytes, far short of the claim.
+        frame.extend_from_slice(&[0x00, 0x00, 0x00]);
+
+        let mut input: &[u8] = &frame;
         let mut output: Vec<u8> = Vec::new();
-        let (r, w) = decompress(&mut input, &mut output, true)?;
-        let ratio = compression_ratio(w, r) as u64;
-        eprintln!("[Decompress] Bytes Read: {}, Bytes Written: {}, Compression Ratio: {:3} %", r, w, ratio);
-        assert_eq!(expected, output);
+        assert!(decompress(&mut input, &mut output, false).is_err());
+    }
+
+    // `flush` must not end the stream: bytes written after a flush still make
+    // it through the round trip.
+    #[test]
+    fn test_flush_keeps_stream_open() -> std::io::Result<()> {
+        use std::io::{Read, Write};
+
+        let mut compressed: Vec<u8> = Vec::new();
+        {
+            let mut writer = CompressWriter::new(&mut compressed);
+            writer.write_all(b"hello ")?;
+            writer.flush()?;
+            writer.write_all(b"world")?;
+            writer.flush()?;
+        }
+
+        let mut reader = DecompressReader::new(&compressed[..]);
+        let mut output: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut output)?;
+
+        assert_eq!(b"hello world".to_vec(), output);
         return Ok(());
     }
 }